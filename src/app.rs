@@ -1,100 +1,290 @@
 use crate::types::{AsyncResult, BoxedSendSyncError};
-use crate::config::AppConfig;
+use crate::config::{AppConfig, FeedConfig};
+use crate::notify::{Notifier, NotifySummary};
+use crate::scheduler::DownloadScheduler;
+use crate::store::DownloadStore;
+use crate::ytdlp::YtDlp;
 
 use futures::stream::{FuturesUnordered, StreamExt as _};
 
 use hyper::body::{self, HttpBody};
-use hyper::{Body, Client, Response, Uri};
+use hyper::{Body, Client, Method, Request, Response, Uri};
 use hyper::client::connect::Connect;
 use hyper_tls::HttpsConnector;
 use tokio::io::AsyncWriteExt as _;
-use tokio::fs::{DirBuilder, OpenOptions, File};
+use tokio::fs::{self, DirBuilder, OpenOptions, File};
+use tokio::sync::Mutex;
 use tokio::task::spawn;
 
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use rss::{Channel, Enclosure, Item};
 
 use std::sync::Arc;
 
-/// Run the RSS Gobbler
+/// Run the RSS Gobbler over every feed in the config, one after another. If
+/// `--watch` is set, loop forever, re-polling every feed on that interval
+/// instead of exiting after one pass.
 pub async fn run(config: AppConfig) -> AsyncResult<()> {
-    // wrap shared config
-    let shared_config = Arc::new(config);
-
-    // set-up http client and get channel listing
+    // set-up http client, shared across all feeds
     let https = HttpsConnector::new();
     let client = Client::builder()
         .build::<_, Body>(https);
-    let channel = get_rss_channel(client.clone(), Arc::clone(&shared_config)).await?;
+
+    // load the download-state store, shared across all feeds
+    let store = Arc::new(Mutex::new(DownloadStore::load(config.get_state_path())?));
+    let scheduler = Arc::new(DownloadScheduler::new(
+        config.get_max_concurrent(),
+        config.get_max_per_host(),
+    ));
+    let ytdlp = Arc::new(build_ytdlp(&config));
+    let notifier = config.get_notify().map(|notify_config| notify_config.build());
+    let force = config.is_force();
+    let use_ytdlp = config.is_use_ytdlp();
+    let watch_interval = config.get_watch_interval();
+    let feeds = config.into_feeds();
+
+    loop {
+        for feed in &feeds {
+            let summary = gobble_feed(
+                Arc::new(feed.clone()),
+                client.clone(),
+                Arc::clone(&store),
+                Arc::clone(&scheduler),
+                Arc::clone(&ytdlp),
+                force,
+                use_ytdlp,
+            )
+            .await
+            .unwrap_or_else(|error| {
+                error!("Failed to gobble feed {}: {}", feed.get_feed_uri(), error);
+                NotifySummary {
+                    feed_title: feed.get_feed_uri().to_string(),
+                    new_episodes: 0,
+                    errors: vec![error.to_string()],
+                }
+            });
+
+            if let Some(notifier) = &notifier {
+                send_notification(notifier.as_ref(), &summary).await;
+            }
+        }
+
+        match watch_interval {
+            Some(interval) => {
+                info!("Cycle complete, sleeping for {:?} before next poll", interval);
+                tokio::time::sleep(interval).await;
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a `YtDlp` runner, applying any `--ytdlp-*` overrides from `config`.
+fn build_ytdlp(config: &AppConfig) -> YtDlp {
+    let mut ytdlp = YtDlp::new();
+    if let Some(program) = config.get_ytdlp_program() {
+        ytdlp = ytdlp.program(program);
+    }
+    if let Some(format) = config.get_ytdlp_format() {
+        ytdlp = ytdlp.format(format);
+    }
+    if let Some(socket_timeout) = config.get_ytdlp_timeout() {
+        ytdlp = ytdlp.socket_timeout(socket_timeout);
+    }
+
+    ytdlp
+}
+
+async fn send_notification(notifier: &dyn Notifier, summary: &NotifySummary) {
+    if let Err(error) = notifier.notify(summary).await {
+        error!("Failed to send notification: {}", error);
+    }
+}
+
+/// Fetch a single feed's channel listing, download every matching enclosure,
+/// and summarize the cycle for the notification hook.
+async fn gobble_feed<C>(
+    config: Arc<FeedConfig>,
+    client: Client<C>,
+    store: Arc<Mutex<DownloadStore>>,
+    scheduler: Arc<DownloadScheduler>,
+    ytdlp: Arc<YtDlp>,
+    force: bool,
+    use_ytdlp: bool,
+) -> AsyncResult<NotifySummary>
+where C: Connect + Clone + Send + Sync + 'static
+{
+    let channel = get_rss_channel(client.clone(), Arc::clone(&config)).await?;
+    let feed_title = channel.title().to_string();
 
     // spawn concurrent downloads and store futures
     let mut downloads = channel
         .items()
         .iter()
         .map(|item| {
-            let (item, client, config) = (item.clone(), client.clone(), Arc::clone(&shared_config));
+            let (item, client, config, store, scheduler, ytdlp) = (
+                item.clone(),
+                client.clone(),
+                Arc::clone(&config),
+                Arc::clone(&store),
+                Arc::clone(&scheduler),
+                Arc::clone(&ytdlp),
+            );
             spawn(
                 async move {
-                    download_enclosure(item, client, config).await
+                    download_enclosure(
+                        item, client, config, store, scheduler, ytdlp, force, use_ytdlp,
+                    )
+                    .await
                 }
             )
         }).collect::<FuturesUnordered<_>>();
-    
-    // await stream until all tasks complete
+
+    // await stream until all tasks complete, tallying new episodes and errors
+    let mut new_episodes = 0usize;
+    let mut errors = Vec::new();
     while let Some(handle) = downloads.next().await {
-        if let Err(error) = handle? {
-            error!("Error: {}", error);
+        match handle? {
+            Ok(true) => new_episodes += 1,
+            Ok(false) => {}
+            Err(error) => {
+                error!("Error: {}", error);
+                errors.push(error.to_string());
+            }
         }
     }
 
-    Ok(())
+    // persist every recorded download from this cycle in a single write,
+    // instead of re-serializing the whole state file per item
+    if new_episodes > 0 {
+        store.lock().await.save()?;
+    }
+
+    info!(
+        feed = %feed_title,
+        new_episodes,
+        error_count = errors.len(),
+        "Completed gobble cycle"
+    );
+
+    Ok(NotifySummary {
+        feed_title,
+        new_episodes,
+        errors,
+    })
 }
 
 
-fn filename_from_title(title: &str) -> String {
+const DEFAULT_EXTENSION: &str = "mp3";
+
+fn filename_from_title(title: &str, extension: &str) -> String {
     let filename = title
         .chars()
         .map(|c| if c.is_alphanumeric() { c } else { '_' })
         .collect::<String>();
 
-    filename + ".mp3"
+    format!("{}.{}", filename, extension)
+}
+
+
+/// Map a (possibly parameterized, e.g. `audio/mpeg; charset=utf-8`) MIME type to
+/// the file extension it's conventionally stored under.
+fn extension_from_mime_type(mime_type: &str) -> Option<&'static str> {
+    match mime_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase().as_str() {
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/aac" | "audio/x-m4a" | "audio/mp4" => Some("m4a"),
+        "video/mp4" => Some("mp4"),
+        "audio/ogg" | "application/ogg" => Some("ogg"),
+        "audio/wav" | "audio/x-wav" => Some("wav"),
+        "application/pdf" => Some("pdf"),
+        _ => None,
+    }
+}
+
+
+/// Fall back to the extension on the enclosure URL's own path, if it has one.
+fn extension_from_url(url: &Uri) -> Option<String> {
+    url.path()
+        .rsplit('/')
+        .next()
+        .and_then(|segment| segment.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .filter(|extension| !extension.is_empty())
+}
+
+
+/// Size in bytes of a previously-started partial download, or 0 if there is none yet.
+async fn partial_file_size(filename: &str, directory: &str) -> AsyncResult<u64> {
+    match fs::metadata(format!("{}/{}", directory, filename)).await {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(error) => Err(error.into()),
+    }
 }
 
 
-async fn create_file_in_dir(filename: &str, directory: &str) -> AsyncResult<File> {
+/// Open `filename`, creating `directory` and the file if they don't exist yet.
+/// When `truncate` is set the file is reset to empty and written from the
+/// start; otherwise new data is appended to whatever is already there.
+async fn open_partial_file(filename: &str, directory: &str, truncate: bool) -> AsyncResult<File> {
     DirBuilder::new()
         .recursive(true)
         .create(directory)
         .await?;
 
-    let io_result = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(format!("{}/{}", directory, filename))
-        .await;
+    let mut options = OpenOptions::new();
+    options.create(true);
+    if truncate {
+        options.write(true).truncate(true);
+    } else {
+        options.append(true);
+    }
 
-    match io_result {
+    match options.open(format!("{}/{}", directory, filename)).await {
         Ok(file) => Ok(file),
         Err(error) => Err(error.into()),
     }
 }
 
 
+/// Result of requesting a (possibly resumed) download: either a body to stream
+/// (`resumed` is true only if the server honoured the `Range` header with a
+/// `206`, meaning the existing `.part` bytes are still valid and can be
+/// appended to), or confirmation from a `416` response that the existing
+/// partial is already complete.
+enum RangeResponse {
+    Body { resp: Response<Body>, resumed: bool },
+    AlreadyComplete,
+}
+
+
 async fn get_redirect_until<C>(
     url: Uri,
     client: Client<C>,
     max_hops: u8,
-) -> AsyncResult<Response<Body>>
+    resume_from: u64,
+) -> AsyncResult<RangeResponse>
 where C: Connect + Clone + Send + Sync + 'static
 {
     let mut location = url.clone();
     for _ in 0..max_hops {
-        let resp = client.get(location.clone()).await?;
+        let mut request_builder = Request::builder().method(Method::GET).uri(location.clone());
+        if resume_from > 0 {
+            request_builder = request_builder.header(hyper::header::RANGE, format!("bytes={}-", resume_from));
+        }
+        let resp = client.request(request_builder.body(Body::empty())?).await?;
         match u16::from(resp.status()) {
             200 => {
-                return Ok(resp);
+                return Ok(RangeResponse::Body { resp, resumed: false });
+            }
+            206 => {
+                return Ok(RangeResponse::Body { resp, resumed: true });
+            }
+            416 => {
+                return Ok(RangeResponse::AlreadyComplete);
             }
             code @ 300..=310 => {
                 let prev = location.clone();
@@ -138,55 +328,250 @@ where C: Connect + Clone + Send + Sync + 'static
 }
 
 
-async fn download_audio_file<C>(
-    url: Uri,
+/// Stream `resp`'s body onto `part_filename`, returning the total bytes written.
+/// `byte_size` is the number of bytes already on disk to append to; pass
+/// `truncate` when the server didn't honour a resume request, so the stale
+/// partial is discarded and the download restarts from zero.
+async fn write_body_to_file(
+    mut resp: Response<Body>,
+    part_filename: &str,
+    directory: &str,
+    mut byte_size: u64,
+    truncate: bool,
+) -> AsyncResult<u64> {
+    if truncate {
+        byte_size = 0;
+    }
+
+    let mut file = open_partial_file(part_filename, directory, truncate).await?;
+    while let Some(chunk) = resp.body_mut().data().await {
+        let chunk = chunk?;
+        byte_size += chunk.len() as u64;
+        file.write_all(&chunk).await?;
+    }
+
+    Ok(byte_size)
+}
+
+
+/// Verify the downloaded size against the enclosure's declared one, if any,
+/// and rename the completed `.part` file into place. Feeds routinely publish
+/// a `length` that is a placeholder or just stale after re-encoding, so a
+/// mismatch is only logged, never treated as a download failure.
+async fn finalize_download(
+    byte_size: u64,
+    expected_bytes: Option<u64>,
+    part_filename: &str,
+    filename: &str,
+    directory: &str,
+) -> AsyncResult<u64> {
+    if let Some(expected_bytes) = expected_bytes {
+        if byte_size != expected_bytes {
+            warn!(
+                "Downloaded size {} does not match enclosure length {} for: {}, keeping it anyway",
+                byte_size, expected_bytes, filename
+            );
+        }
+    }
+
+    fs::rename(
+        format!("{}/{}", directory, part_filename),
+        format!("{}/{}", directory, filename),
+    )
+    .await?;
+    info!("Download complete: {}", filename);
+
+    Ok(byte_size)
+}
+
+
+/// Resolve `page_url` through yt-dlp and download the media it points to.
+async fn download_via_ytdlp<C>(
     title: &str,
+    page_url: &str,
     client: Client<C>,
-    config: Arc<AppConfig>,
-) -> AsyncResult<()>
+    config: Arc<FeedConfig>,
+    expected_bytes: Option<u64>,
+    ytdlp: &YtDlp,
+) -> AsyncResult<u64>
 where C: Connect + Clone + Send + Sync + 'static
 {
-    let filename = filename_from_title(title);
-    let mut file = create_file_in_dir(&filename, &config.get_output_directory()).await?;
+    info!("Resolving stream URL via yt-dlp: {}", page_url);
+    let resolved = ytdlp.resolve(page_url).await?;
+    let resolved_uri: Uri = resolved.url.parse()?;
+    let extension = resolved
+        .ext
+        .or_else(|| extension_from_url(&resolved_uri))
+        .unwrap_or_else(|| DEFAULT_EXTENSION.to_string());
+
+    let filename = filename_from_title(title, &extension);
+    let part_filename = format!("{}.part", &filename);
+    let directory = config.get_output_directory();
+
+    let byte_size = partial_file_size(&part_filename, &directory).await?;
 
     info!("Downloading file: {}", &filename);
-    let mut resp = get_redirect_until(url, client, 10).await?;
-    while let Some(chunk) = resp.body_mut().data().await {
-        file.write_all(&chunk?).await?;
+    let byte_size = match get_redirect_until(resolved_uri, client, 10, byte_size).await? {
+        RangeResponse::AlreadyComplete => {
+            info!("Partial download already complete: {}", &filename);
+            byte_size
+        }
+        RangeResponse::Body { resp, resumed } => {
+            if !resumed && byte_size > 0 {
+                info!(
+                    "Server ignored resume request, restarting download from scratch: {}",
+                    &filename
+                );
+            }
+            write_body_to_file(resp, &part_filename, &directory, byte_size, !resumed).await?
+        }
+    };
+
+    finalize_download(byte_size, expected_bytes, &part_filename, &filename, &directory).await
+}
+
+
+async fn download_audio_file<C>(
+    url: Uri,
+    title: &str,
+    mime_type: &str,
+    page_url: &str,
+    client: Client<C>,
+    config: Arc<FeedConfig>,
+    expected_bytes: Option<u64>,
+    use_ytdlp: bool,
+    ytdlp: Arc<YtDlp>,
+) -> AsyncResult<u64>
+where C: Connect + Clone + Send + Sync + 'static
+{
+    if use_ytdlp {
+        return download_via_ytdlp(title, page_url, client, config, expected_bytes, &ytdlp).await;
     }
-    info!("Download complete: {}", &filename);
 
-    Ok(())
+    let provisional_extension = extension_from_mime_type(mime_type)
+        .map(String::from)
+        .or_else(|| extension_from_url(&url))
+        .unwrap_or_else(|| DEFAULT_EXTENSION.to_string());
+
+    let provisional_filename = filename_from_title(title, &provisional_extension);
+    let part_filename = format!("{}.part", &provisional_filename);
+    let directory = config.get_output_directory();
+
+    let mut byte_size = partial_file_size(&part_filename, &directory).await?;
+
+    info!("Downloading file: {}", &provisional_filename);
+    let extension = match get_redirect_until(url, client.clone(), 10, byte_size).await? {
+        RangeResponse::AlreadyComplete => {
+            info!("Partial download already complete: {}", &provisional_filename);
+            provisional_extension
+        }
+        RangeResponse::Body { resp, resumed } => {
+            // confirm against the real Content-Type now that redirects are resolved
+            let content_type = resp
+                .headers()
+                .get(hyper::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+
+            if content_type.split(';').next().unwrap_or("").trim() == "text/html" {
+                info!(
+                    "Enclosure served HTML instead of media, falling back to yt-dlp: {}",
+                    page_url
+                );
+                return download_via_ytdlp(title, page_url, client, config, expected_bytes, &ytdlp)
+                    .await;
+            }
+
+            if !resumed && byte_size > 0 {
+                info!(
+                    "Server ignored resume request, restarting download from scratch: {}",
+                    &provisional_filename
+                );
+            }
+
+            let extension = extension_from_mime_type(&content_type)
+                .map(String::from)
+                .unwrap_or(provisional_extension);
+
+            byte_size = write_body_to_file(resp, &part_filename, &directory, byte_size, !resumed).await?;
+            extension
+        }
+    };
+
+    let filename = filename_from_title(title, &extension);
+    finalize_download(byte_size, expected_bytes, &part_filename, &filename, &directory).await
 }
 
 
 async fn download_enclosure<C>(
     item: Item,
     client: Client<C>,
-    config: Arc<AppConfig>,
-) -> AsyncResult<()>
+    config: Arc<FeedConfig>,
+    store: Arc<Mutex<DownloadStore>>,
+    scheduler: Arc<DownloadScheduler>,
+    ytdlp: Arc<YtDlp>,
+    force: bool,
+    use_ytdlp: bool,
+) -> AsyncResult<bool>
 where C: Connect + Clone + Send + Sync + 'static
 {
     if let Item {
         title: Some(title),
-        enclosure: Some(Enclosure { url, .. }),
+        enclosure: Some(Enclosure { url, length, mime_type }),
+        guid,
+        link,
         ..
     } = item
     {
         info!("Parsed RSS item: {} with enclosure at: {}", &title, &url);
-        if config.is_pattern_valid(&title) {
-            download_audio_file(url.parse()?, &title, client, config).await
-        } else {
+        if !config.is_pattern_valid(&title) {
             info!("Skipping due to regex rules: {}", &title);
-            Ok(())
+            return Ok(false);
         }
+
+        let feed_url = config.get_feed_uri().to_string();
+        let key = guid.map(|guid| guid.value().to_string()).unwrap_or_else(|| url.clone());
+
+        if !force && store.lock().await.is_downloaded(&feed_url, &key) {
+            info!("Skipping already-downloaded item: {}", &title);
+            return Ok(false);
+        }
+
+        let page_url = link.unwrap_or_else(|| url.clone());
+
+        // enclosures very commonly declare a placeholder length of "0"; treat
+        // that as "no declared size" rather than an expected empty file
+        let expected_bytes = length.parse::<u64>().ok().filter(|&n| n > 0);
+        let uri: Uri = url.parse()?;
+        let host = uri.host().unwrap_or("unknown").to_string();
+        let permits = scheduler.acquire(&host).await;
+        let byte_size = download_audio_file(
+            uri,
+            &title,
+            &mime_type,
+            &page_url,
+            client,
+            config,
+            expected_bytes,
+            use_ytdlp,
+            ytdlp,
+        )
+        .await?;
+        drop(permits);
+
+        store
+            .lock()
+            .await
+            .record_download(&feed_url, &key, &title, byte_size);
+        Ok(true)
     } else {
         Err(format!("Failed to parse RSS item: {:?}", item).into())
     }
 }
 
 
-async fn get_rss_channel<C>(client: Client<C>, config: Arc<AppConfig>) -> AsyncResult<Channel>
+async fn get_rss_channel<C>(client: Client<C>, config: Arc<FeedConfig>) -> AsyncResult<Channel>
 where C: Connect + Clone + Send + Sync + 'static
 {
     let resp = client.get(config.get_feed_uri()).await?;
@@ -1,30 +1,40 @@
+use crate::notify::NotifyConfig;
 use crate::AsyncResult;
 
 use clap::{App, Arg};
 use hyper::Uri;
 use regex::Regex;
+use serde::Deserialize;
+
+use std::fs;
+use std::time::Duration;
 
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 const APP_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 const APP_DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 const DEFAULT_DIRECTORY: &str = "episodes";
+const DEFAULT_STATE_PATH: &str = "gobbler_state.json";
+const DEFAULT_MAX_CONCURRENT: &str = "4";
+const DEFAULT_MAX_PER_HOST: &str = "2";
 
-#[derive(Debug)]
-pub struct AppConfig {
+/// Configuration for a single feed: where to fetch it from, where to store its
+/// episodes, and which episodes to keep.
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
     feed_uri: Uri,
     output_path: String,
     option_include_regex: Option<Regex>,
     option_exclude_regex: Option<Regex>,
 }
 
-impl AppConfig {
-    /// Constructs a new AppConfig including compiled regular expressions given input patterns
+impl FeedConfig {
+    /// Constructs a new FeedConfig including compiled regular expressions given input patterns
     pub fn new(
         feed_url: &str,
         output_path: &str,
         include_pattern: Option<&str>,
         exclude_pattern: Option<&str>,
-    ) -> Result<AppConfig, regex::Error> {
+    ) -> Result<FeedConfig, regex::Error> {
         let option_include_regex = match include_pattern {
             Some(pattern) => Some(Regex::new(pattern)?),
             None => None,
@@ -37,7 +47,7 @@ impl AppConfig {
 
         let feed_uri = feed_url.parse().unwrap();
 
-        Ok(AppConfig {
+        Ok(FeedConfig {
             feed_uri,
             output_path: String::from(output_path),
             option_include_regex,
@@ -68,21 +78,172 @@ impl AppConfig {
 
         include && !exclude
     }
+}
+
+/// A single feed entry as written in a `--config` YAML file. Any field left
+/// unset falls back to the file's top-level `defaults`.
+#[derive(Debug, Deserialize)]
+struct FeedEntry {
+    feed_url: String,
+    output_path: Option<String>,
+    include: Option<String>,
+    exclude: Option<String>,
+}
+
+/// Top-level defaults applied to every `FeedEntry` that omits a field.
+#[derive(Debug, Deserialize, Default)]
+struct FeedDefaults {
+    output_path: Option<String>,
+    include: Option<String>,
+    exclude: Option<String>,
+}
+
+/// Shape of the YAML document passed via `--config`.
+#[derive(Debug, Deserialize)]
+struct FeedFile {
+    #[serde(default)]
+    defaults: FeedDefaults,
+    feeds: Vec<FeedEntry>,
+    notify: Option<NotifyConfig>,
+}
+
+/// The list of feeds to gobble this run, either parsed from a `--config` YAML
+/// file or built as a single entry from CLI flags, plus settings that apply
+/// across all feeds.
+#[derive(Debug)]
+pub struct AppConfig {
+    feeds: Vec<FeedConfig>,
+    force: bool,
+    state_path: String,
+    max_concurrent: usize,
+    max_per_host: usize,
+    use_ytdlp: bool,
+    ytdlp_program: Option<String>,
+    ytdlp_format: Option<String>,
+    ytdlp_timeout: Option<Duration>,
+    watch_interval: Option<Duration>,
+    notify: Option<NotifyConfig>,
+}
+
+impl AppConfig {
+    pub fn into_feeds(self) -> Vec<FeedConfig> {
+        self.feeds
+    }
+
+    /// Whether already-downloaded episodes should be re-downloaded anyway.
+    pub fn is_force(&self) -> bool {
+        self.force
+    }
+
+    pub fn get_state_path(&self) -> &str {
+        &self.state_path
+    }
+
+    /// Maximum number of downloads allowed to run at once, across all feeds.
+    pub fn get_max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// Maximum number of concurrent downloads allowed against a single host.
+    pub fn get_max_per_host(&self) -> usize {
+        self.max_per_host
+    }
+
+    /// Whether every enclosure should be resolved through yt-dlp instead of
+    /// downloaded directly.
+    pub fn is_use_ytdlp(&self) -> bool {
+        self.use_ytdlp
+    }
+
+    /// Override for the yt-dlp executable's name/path, if `--ytdlp-program` was given.
+    pub fn get_ytdlp_program(&self) -> Option<&str> {
+        self.ytdlp_program.as_deref()
+    }
+
+    /// Override for yt-dlp's `-f` format selector, if `--ytdlp-format` was given.
+    pub fn get_ytdlp_format(&self) -> Option<&str> {
+        self.ytdlp_format.as_deref()
+    }
+
+    /// Override for yt-dlp's socket timeout, if `--ytdlp-timeout` was given.
+    pub fn get_ytdlp_timeout(&self) -> Option<Duration> {
+        self.ytdlp_timeout
+    }
+
+    /// If `--watch` was given, the interval on which feeds are re-polled
+    /// instead of gobbling once and exiting.
+    pub fn get_watch_interval(&self) -> Option<Duration> {
+        self.watch_interval
+    }
+
+    /// The notification backend to report each gobble cycle's results to, if
+    /// one was configured in the YAML `notify` section.
+    pub fn get_notify(&self) -> Option<&NotifyConfig> {
+        self.notify.as_ref()
+    }
+
+    /// Parse a `--config` YAML file describing a list of feeds, merging each
+    /// entry with the file's `defaults`, along with its top-level `notify`
+    /// section, if present.
+    fn parse_config_file(path: &str) -> AsyncResult<(Vec<FeedConfig>, Option<NotifyConfig>)> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read config file {}: {}", path, err))?;
+        let file: FeedFile = serde_yaml::from_str(&contents)
+            .map_err(|err| format!("Failed to parse config file {}: {}", path, err))?;
+
+        let feeds = file
+            .feeds
+            .iter()
+            .map(|entry| {
+                let output_path = entry
+                    .output_path
+                    .as_deref()
+                    .or(file.defaults.output_path.as_deref())
+                    .unwrap_or(DEFAULT_DIRECTORY);
+                let include_pattern = entry
+                    .include
+                    .as_deref()
+                    .or(file.defaults.include.as_deref());
+                let exclude_pattern = entry
+                    .exclude
+                    .as_deref()
+                    .or(file.defaults.exclude.as_deref());
+
+                FeedConfig::new(&entry.feed_url, output_path, include_pattern, exclude_pattern)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Failed to build FeedConfig from {}: {}", path, err))?;
+
+        Ok((feeds, file.notify))
+    }
 
-    /// Parse clap commandline arguments, and construct a new AppConfig
+    /// Parse clap commandline arguments, and construct a new AppConfig.
+    ///
+    /// If `--config` is given, it is read as a YAML file describing the full
+    /// feed list. Otherwise the `--feed`/`--dir`/`--include`/`--exclude` flags
+    /// are treated as a single-entry override, preserving the original
+    /// one-feed-per-process behaviour.
     pub fn from_cli_args() -> AsyncResult<AppConfig> {
         // parse cmdline args
         let matches = App::new("RSS Gobbler")
             .version(APP_VERSION)
             .author(APP_AUTHORS)
             .about(APP_DESCRIPTION)
+            .arg(
+                Arg::with_name("config_path")
+                    .short("c")
+                    .long("config")
+                    .value_name("PATH")
+                    .help("Path to a YAML file describing a list of feeds to gobble.")
+                    .required(false),
+            )
             .arg(
                 Arg::with_name("feed_url")
                     .short("f")
                     .long("feed")
                     .value_name("URL")
                     .help("The URL of the RSS feed to download.")
-                    .required(true),
+                    .required_unless("config_path"),
             )
             .arg(
                 Arg::with_name("directory")
@@ -108,16 +269,122 @@ impl AppConfig {
                     .help("An optional regex pattern to for episodes to exclude.")
                     .required(false),
             )
+            .arg(
+                Arg::with_name("force")
+                    .long("force")
+                    .help("Re-download episodes even if already recorded as downloaded.")
+                    .takes_value(false),
+            )
+            .arg(
+                Arg::with_name("state_path")
+                    .long("state")
+                    .value_name("PATH")
+                    .help("Path to the download-state file used to skip already-fetched episodes.")
+                    .default_value(DEFAULT_STATE_PATH),
+            )
+            .arg(
+                Arg::with_name("max_concurrent")
+                    .long("max-concurrent")
+                    .value_name("N")
+                    .help("Maximum number of downloads to run at once, across all feeds.")
+                    .default_value(DEFAULT_MAX_CONCURRENT),
+            )
+            .arg(
+                Arg::with_name("max_per_host")
+                    .long("max-per-host")
+                    .value_name("N")
+                    .help("Maximum number of concurrent downloads allowed against a single host.")
+                    .default_value(DEFAULT_MAX_PER_HOST),
+            )
+            .arg(
+                Arg::with_name("use_ytdlp")
+                    .long("use-ytdlp")
+                    .help("Resolve every enclosure through yt-dlp instead of downloading it directly.")
+                    .takes_value(false),
+            )
+            .arg(
+                Arg::with_name("watch_interval")
+                    .long("watch")
+                    .value_name("MINUTES")
+                    .help("Run forever, re-polling every feed this many minutes apart, instead of gobbling once and exiting.")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("ytdlp_program")
+                    .long("ytdlp-program")
+                    .value_name("PROGRAM")
+                    .help("Name or path of the yt-dlp executable to run (default: yt-dlp).")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("ytdlp_format")
+                    .long("ytdlp-format")
+                    .value_name("FORMAT")
+                    .help("yt-dlp -f format selector to resolve enclosures with (default: best).")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("ytdlp_timeout")
+                    .long("ytdlp-timeout")
+                    .value_name("SECONDS")
+                    .help("Timeout in seconds for yt-dlp to resolve a stream URL (default: 30).")
+                    .required(false),
+            )
             .get_matches();
-        let feed_url = matches.value_of("feed_url").unwrap();
-        let output_path = matches.value_of("feed_url").unwrap();
-        let include_pattern = matches.value_of("include_pattern");
-        let exclude_pattern = matches.value_of("exclude_pattern");
-        // compile regex and return config
-        match AppConfig::new(feed_url, output_path, include_pattern, exclude_pattern) {
-            Ok(config) => Ok(config),
-            Err(err) => Err(format!("Failed to build AppConfig: {}", err).into()),
-        }
+
+        let force = matches.is_present("force");
+        let state_path = matches.value_of("state_path").unwrap().to_string();
+        let max_concurrent = matches
+            .value_of("max_concurrent")
+            .unwrap()
+            .parse()
+            .map_err(|err| format!("Invalid --max-concurrent: {}", err))?;
+        let max_per_host = matches
+            .value_of("max_per_host")
+            .unwrap()
+            .parse()
+            .map_err(|err| format!("Invalid --max-per-host: {}", err))?;
+        let use_ytdlp = matches.is_present("use_ytdlp");
+        let ytdlp_program = matches.value_of("ytdlp_program").map(String::from);
+        let ytdlp_format = matches.value_of("ytdlp_format").map(String::from);
+        let ytdlp_timeout = matches
+            .value_of("ytdlp_timeout")
+            .map(|seconds| seconds.parse::<u64>())
+            .transpose()
+            .map_err(|err| format!("Invalid --ytdlp-timeout: {}", err))?
+            .map(Duration::from_secs);
+        let watch_interval = matches
+            .value_of("watch_interval")
+            .map(|minutes| minutes.parse::<u64>())
+            .transpose()
+            .map_err(|err| format!("Invalid --watch: {}", err))?
+            .map(|minutes| Duration::from_secs(minutes * 60));
+
+        let (feeds, notify) = if let Some(config_path) = matches.value_of("config_path") {
+            AppConfig::parse_config_file(config_path)?
+        } else {
+            let feed_url = matches.value_of("feed_url").unwrap();
+            let output_path = matches.value_of("directory").unwrap();
+            let include_pattern = matches.value_of("include_pattern");
+            let exclude_pattern = matches.value_of("exclude_pattern");
+            let feed = FeedConfig::new(feed_url, output_path, include_pattern, exclude_pattern)
+                .map_err(|err| format!("Failed to build AppConfig: {}", err))?;
+            (vec![feed], None)
+        };
+
+        Ok(AppConfig {
+            feeds,
+            force,
+            state_path,
+            max_concurrent,
+            max_per_host,
+            use_ytdlp,
+            ytdlp_program,
+            ytdlp_format,
+            ytdlp_timeout,
+            watch_interval,
+            notify,
+        })
     }
 }
 
@@ -125,13 +392,13 @@ impl AppConfig {
 mod tests {
     use super::*;
 
-    fn mock_appconfig(has_some_regex: bool) -> AppConfig {
+    fn mock_feedconfig(has_some_regex: bool) -> FeedConfig {
         let feed_url = "https://rss.example.com/podcast";
         let output_path = "/output/path";
         let include_pattern = r"^dog";
         let exclude_pattern = r"c.*t";
 
-        AppConfig::new(
+        FeedConfig::new(
             feed_url,
             output_path,
             if has_some_regex {
@@ -149,13 +416,13 @@ mod tests {
     }
 
     #[test]
-    fn test_appconfig_regex() {
-        let config_none = mock_appconfig(false);
+    fn test_feedconfig_regex() {
+        let config_none = mock_feedconfig(false);
         assert_eq!(config_none.is_pattern_valid("dog episode"), true);
         assert_eq!(config_none.is_pattern_valid("episode"), true);
         assert_eq!(config_none.is_pattern_valid("dog casdat"), true);
 
-        let config_some = mock_appconfig(true);
+        let config_some = mock_feedconfig(true);
         assert_eq!(config_some.is_pattern_valid("dog episode"), true); // meets start with dog
         assert_eq!(config_some.is_pattern_valid("episode"), false); // doesn't start with dog
         assert_eq!(config_some.is_pattern_valid("dog casdat"), false); // starts with dog but contains c.*t
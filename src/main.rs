@@ -1,6 +1,10 @@
 mod app;
 mod config;
+mod notify;
+mod scheduler;
+mod store;
 mod types;
+mod ytdlp;
 
 use crate::app::run;
 use crate::config::AppConfig;
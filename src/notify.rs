@@ -0,0 +1,148 @@
+use crate::types::AsyncResult;
+
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use serde::{Deserialize, Serialize};
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// Summary of one feed's gobble cycle, handed to a `Notifier` on completion.
+#[derive(Debug, Clone, Serialize)]
+pub struct NotifySummary {
+    pub feed_title: String,
+    pub new_episodes: usize,
+    pub errors: Vec<String>,
+}
+
+fn format_summary(summary: &NotifySummary) -> String {
+    let mut text = format!(
+        "{}: {} new episode(s)",
+        summary.feed_title, summary.new_episodes
+    );
+
+    if !summary.errors.is_empty() {
+        text.push_str(&format!(
+            ", {} error(s): {}",
+            summary.errors.len(),
+            summary.errors.join("; ")
+        ));
+    }
+
+    text
+}
+
+/// A backend that can deliver a `NotifySummary` somewhere, e.g. a webhook or a
+/// chat app. Implement this to add a new backend.
+pub trait Notifier: Send + Sync {
+    fn notify<'a>(
+        &'a self,
+        summary: &'a NotifySummary,
+    ) -> Pin<Box<dyn Future<Output = AsyncResult<()>> + Send + 'a>>;
+}
+
+/// Which notification backend to use, as configured in the YAML `notify` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum NotifyConfig {
+    Webhook { url: String },
+    Telegram { bot_token: String, chat_id: String },
+}
+
+impl NotifyConfig {
+    pub fn build(&self) -> Box<dyn Notifier> {
+        match self {
+            NotifyConfig::Webhook { url } => Box::new(WebhookNotifier::new(url.clone())),
+            NotifyConfig::Telegram { bot_token, chat_id } => {
+                Box::new(TelegramNotifier::new(bot_token.clone(), chat_id.clone()))
+            }
+        }
+    }
+}
+
+struct WebhookNotifier {
+    client: Client<HttpsConnector<HttpConnector>>,
+    url: String,
+}
+
+impl WebhookNotifier {
+    fn new(url: String) -> WebhookNotifier {
+        WebhookNotifier {
+            client: Client::builder().build(HttpsConnector::new()),
+            url,
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(
+        &'a self,
+        summary: &'a NotifySummary,
+    ) -> Pin<Box<dyn Future<Output = AsyncResult<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let uri: Uri = self.url.parse()?;
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(summary)?))?;
+
+            let resp = self.client.request(request).await?;
+            if !resp.status().is_success() {
+                return Err(format!("Webhook notification failed with status: {}", resp.status()).into());
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct TelegramMessage<'a> {
+    chat_id: &'a str,
+    text: String,
+}
+
+struct TelegramNotifier {
+    client: Client<HttpsConnector<HttpConnector>>,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    fn new(bot_token: String, chat_id: String) -> TelegramNotifier {
+        TelegramNotifier {
+            client: Client::builder().build(HttpsConnector::new()),
+            bot_token,
+            chat_id,
+        }
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn notify<'a>(
+        &'a self,
+        summary: &'a NotifySummary,
+    ) -> Pin<Box<dyn Future<Output = AsyncResult<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let uri: Uri = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token).parse()?;
+            let message = TelegramMessage {
+                chat_id: &self.chat_id,
+                text: format_summary(summary),
+            };
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&message)?))?;
+
+            let resp = self.client.request(request).await?;
+            if !resp.status().is_success() {
+                return Err(format!("Telegram notification failed with status: {}", resp.status()).into());
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,47 @@
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Bounds how many downloads run at once, both overall and per resolved host,
+/// so a large feed can't open hundreds of simultaneous connections or hammer
+/// a single CDN.
+pub struct DownloadScheduler {
+    global: Arc<Semaphore>,
+    per_host: Mutex<HashMap<String, Arc<Semaphore>>>,
+    max_per_host: usize,
+}
+
+impl DownloadScheduler {
+    pub fn new(max_concurrent: usize, max_per_host: usize) -> DownloadScheduler {
+        DownloadScheduler {
+            global: Arc::new(Semaphore::new(max_concurrent)),
+            per_host: Mutex::new(HashMap::new()),
+            max_per_host,
+        }
+    }
+
+    /// Acquire a global permit and a per-host permit for `host`, waiting until both
+    /// are available. Drop the returned permits once the download completes.
+    pub async fn acquire(&self, host: &str) -> (OwnedSemaphorePermit, OwnedSemaphorePermit) {
+        let global_permit = Arc::clone(&self.global)
+            .acquire_owned()
+            .await
+            .expect("global semaphore should never be closed");
+
+        let host_semaphore = {
+            let mut per_host = self.per_host.lock().await;
+            Arc::clone(
+                per_host
+                    .entry(host.to_string())
+                    .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host))),
+            )
+        };
+        let host_permit = host_semaphore
+            .acquire_owned()
+            .await
+            .expect("per-host semaphore should never be closed");
+
+        (global_permit, host_permit)
+    }
+}
@@ -0,0 +1,71 @@
+use crate::types::AsyncResult;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A single previously-completed download, recorded so future runs can skip it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    pub title: String,
+    pub byte_size: u64,
+    pub completed_at: u64,
+}
+
+/// On-disk index of completed downloads, keyed by feed URL and then by each
+/// item's GUID (or enclosure URL if no GUID is present).
+#[derive(Debug, Default)]
+pub struct DownloadStore {
+    path: String,
+    feeds: HashMap<String, HashMap<String, DownloadRecord>>,
+}
+
+impl DownloadStore {
+    /// Load the store from `path`, or start an empty one if the file doesn't exist yet.
+    pub fn load(path: &str) -> AsyncResult<DownloadStore> {
+        let feeds = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|err| format!("Failed to parse state file {}: {}", path, err))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(DownloadStore {
+            path: path.to_string(),
+            feeds,
+        })
+    }
+
+    /// Persist the store back to disk.
+    pub fn save(&self) -> AsyncResult<()> {
+        let contents = serde_json::to_string_pretty(&self.feeds)?;
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+
+    /// True if `key` (GUID or enclosure URL) is already recorded as downloaded for `feed_url`.
+    pub fn is_downloaded(&self, feed_url: &str, key: &str) -> bool {
+        self.feeds
+            .get(feed_url)
+            .map_or(false, |items| items.contains_key(key))
+    }
+
+    /// Record a completed download for `feed_url`/`key`.
+    pub fn record_download(&mut self, feed_url: &str, key: &str, title: &str, byte_size: u64) {
+        let completed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        self.feeds.entry(feed_url.to_string()).or_insert_with(HashMap::new).insert(
+            key.to_string(),
+            DownloadRecord {
+                title: title.to_string(),
+                byte_size,
+                completed_at,
+            },
+        );
+    }
+}
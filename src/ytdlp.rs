@@ -0,0 +1,103 @@
+use crate::types::AsyncResult;
+
+use serde::Deserialize;
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use tracing::error;
+
+const DEFAULT_PROGRAM: &str = "yt-dlp";
+const DEFAULT_FORMAT: &str = "best";
+const DEFAULT_SOCKET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The subset of yt-dlp's `-J` JSON output needed to resolve a webpage link
+/// into a direct, downloadable media URL.
+#[derive(Debug, Deserialize)]
+pub struct YtDlpResult {
+    pub url: String,
+    #[serde(default)]
+    pub ext: Option<String>,
+}
+
+/// Builds and runs a `yt-dlp` invocation that resolves a webpage enclosure
+/// into a direct media URL.
+#[derive(Debug, Clone)]
+pub struct YtDlp {
+    program: String,
+    format: String,
+    socket_timeout: Duration,
+}
+
+impl YtDlp {
+    pub fn new() -> YtDlp {
+        YtDlp {
+            program: DEFAULT_PROGRAM.to_string(),
+            format: DEFAULT_FORMAT.to_string(),
+            socket_timeout: DEFAULT_SOCKET_TIMEOUT,
+        }
+    }
+
+    pub fn program<S: Into<String>>(mut self, program: S) -> YtDlp {
+        self.program = program.into();
+        self
+    }
+
+    pub fn format<S: Into<String>>(mut self, format: S) -> YtDlp {
+        self.format = format.into();
+        self
+    }
+
+    pub fn socket_timeout(mut self, socket_timeout: Duration) -> YtDlp {
+        self.socket_timeout = socket_timeout;
+        self
+    }
+
+    /// Resolve `page_url` to a direct media URL by running `yt-dlp -J` and
+    /// parsing its JSON output.
+    pub async fn resolve(&self, page_url: &str) -> AsyncResult<YtDlpResult> {
+        let output = timeout(
+            self.socket_timeout,
+            Command::new(&self.program)
+                .arg("-J")
+                .arg("--no-playlist")
+                .arg("--socket-timeout")
+                .arg(self.socket_timeout.as_secs().to_string())
+                .arg("-f")
+                .arg(&self.format)
+                .arg(page_url)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output(),
+        )
+        .await
+        .map_err(|_| format!("{} timed out resolving: {}", &self.program, page_url))??;
+
+        if !output.status.success() {
+            error!(
+                "{} failed for {}: {}",
+                &self.program,
+                page_url,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return Err(format!(
+                "{} exited with {} resolving: {}",
+                &self.program, output.status, page_url
+            )
+            .into());
+        }
+
+        serde_json::from_slice(&output.stdout).map_err(|err| {
+            format!("Failed to parse {} output for {}: {}", &self.program, page_url, err).into()
+        })
+    }
+}
+
+impl Default for YtDlp {
+    fn default() -> YtDlp {
+        YtDlp::new()
+    }
+}